@@ -1,28 +1,116 @@
 use crate::{
-    smb_process::{GameState, SmbProcess},
-    Settings,
+    level::Level,
+    level_splits::LevelSplits,
+    runtime::{AsrRuntime, Runtime},
+    smb_process::{GameMemory, GameState, Memory, SmbProcess},
+    GameTimeMode, Settings, TickRate,
 };
-use asr::{settings::Gui, timer::TimerState};
-use core::f32;
+use arrayvec::{ArrayString, ArrayVec};
+use asr::{time::Duration, timer::TimerState};
+use core::{f32, fmt::Write, iter, ops::Range};
+
+/// An event which causes a split.
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum Milestone {
+    Level(Level),
+    BossEntrance { world: u8 },
+    BossUnlock { world: u8 },
+    CharacterUnlock { character_count: i32 },
+    FinalCutscene,
+}
+
+impl Milestone {
+    /// Whether the milestone can legitimately be reached multiple times in a run.
+    fn is_repeatable(self) -> bool {
+        matches!(self, Self::Level(Level::WarpZone { .. }))
+    }
+}
+
+/// A level completed during a run.
+#[derive(Clone, Copy)]
+struct LevelRecord {
+    level: Level,
+    level_time: f32,
+    deaths: i32,
+}
 
-pub(crate) struct AutoSplitter<'settings> {
-    process: SmbProcess,
+impl LevelRecord {
+    /// The level time, limited so that level times read from garbage memory can still
+    /// be formatted in a fixed amount of space.
+    fn bounded_level_time(self) -> f32 {
+        self.level_time.clamp(-1e6, 1e6)
+    }
+}
+
+pub(crate) struct AutoSplitter<'settings, M = GameMemory, R = AsrRuntime> {
+    process: SmbProcess<M>,
+    runtime: R,
     settings: &'settings mut Settings,
+    level_splits: &'settings mut LevelSplits,
+    /// Changed settings which are applied once the timer is reset.
+    pending_settings: Option<(Settings, LevelSplits)>,
     timer_previous_state: TimerState,
+    timer_previous_split_index: Option<u64>,
+    /// The milestones which caused splits in this run, with the index of the split.
+    split_milestones: ArrayVec<(Option<u64>, Milestone), 256>,
+    level_history: ArrayVec<LevelRecord, 512>,
+    tick: u64,
+    tick_rate: TickRate,
+    was_recording_trace: bool,
     level_time: f32,
+    level_time_sum: f64,
+    /// The best sum of level times stored for the category, as of the start of the
+    /// run.
+    best_level_time_sum: Option<f64>,
     death_count_offset: i32,
+    /// The death count at the start of every split of the run, indexed by split
+    /// index. Outside of a run only the first entry is used.
+    split_death_count_offsets: ArrayVec<i32, 256>,
+    level_death_count_offset: i32,
+    warp_zones_entered: i32,
+    warp_zones_completed: i32,
+    /// The tick on which a level was finished without `level_beaten` or
+    /// `level_transition` confirming it yet.
+    unconfirmed_level_finish_tick: Option<u64>,
 }
 
-impl<'settings> AutoSplitter<'settings> {
+impl<'settings, M: Memory, R: Runtime> AutoSplitter<'settings, M, R> {
     const DUMMY_LEVEL_TIME: f32 = 1e8;
-
-    pub(crate) fn new(process: SmbProcess, settings: &'settings mut Settings) -> Self {
+    /// The number of consecutive failed reads of a variable after which the failure is
+    /// reported.
+    const READ_FAILURE_REPORT_TICKS: u32 = 120;
+    /// The plausible sums of level times, in seconds.
+    const LEVEL_TIME_SUM_RANGE: Range<f64> = 0.0..1e9;
+
+    pub(crate) fn new(
+        process: SmbProcess<M>,
+        runtime: R,
+        settings: &'settings mut Settings,
+        level_splits: &'settings mut LevelSplits,
+    ) -> Self {
+        let tick_rate = settings.tick_rate;
         let mut this = Self {
             process,
+            runtime,
             settings,
+            level_splits,
+            pending_settings: None,
             level_time: f32::NAN,
+            level_time_sum: 0.0,
+            best_level_time_sum: None,
             death_count_offset: 0,
+            split_death_count_offsets: ArrayVec::new(),
+            level_death_count_offset: 0,
+            warp_zones_entered: 0,
+            warp_zones_completed: 0,
+            unconfirmed_level_finish_tick: None,
             timer_previous_state: TimerState::Unknown,
+            timer_previous_split_index: None,
+            split_milestones: ArrayVec::new(),
+            level_history: ArrayVec::new(),
+            tick: 0,
+            tick_rate,
+            was_recording_trace: false,
         };
         this.init();
         this
@@ -32,47 +120,132 @@ impl<'settings> AutoSplitter<'settings> {
         self.process.is_running()
     }
 
+    pub(crate) fn update_settings(&mut self) {
+        let (mut settings, mut level_splits) = self
+            .pending_settings
+            .take()
+            .unwrap_or_else(|| (self.settings.clone(), self.level_splits.clone()));
+        self.runtime.load_settings(&mut settings, &mut level_splits);
+
+        // Changing the other settings during a run could change when the timer is
+        // started or split, so they only take effect after a reset.
+        self.settings.update_live_settings_from(&settings);
+        if self.runtime.timer_state() == TimerState::NotRunning {
+            *self.settings = settings;
+            *self.level_splits = level_splits;
+        } else {
+            self.pending_settings = Some((settings, level_splits));
+        }
+
+        if self.settings.tick_rate != self.tick_rate {
+            self.set_tick_rate();
+        }
+    }
+
     pub(crate) fn run_tick(&mut self) {
-        self.settings.update();
         self.process.update_values();
+        self.report_read_failures();
+
+        if self.settings.record_trace {
+            self.record_trace();
+        }
+        self.was_recording_trace = self.settings.record_trace;
+        self.tick += 1;
+
+        // The timer may have been controlled manually since the last tick
+        self.observe_timer();
 
         self.update();
+        self.update_game_time();
 
-        if matches!(
-            asr::timer::state(),
-            TimerState::Running | TimerState::Paused
-        ) && self.reset()
+        let timer_state = self.runtime.timer_state();
+        if (matches!(timer_state, TimerState::Running | TimerState::Paused) && self.reset())
+            || (timer_state == TimerState::Ended && self.settings.il_mode && self.start())
         {
-            asr::timer::reset();
-            return;
+            self.runtime.reset();
+            self.observe_timer();
+
+            // In IL mode, the next attempt may start on the same tick
+            if !self.settings.il_mode {
+                return;
+            }
+        }
+
+        if let Some(milestone) = self
+            .reached_milestones()
+            .into_iter()
+            .find(|&milestone| !self.is_split_suppressed(milestone))
+        {
+            let split_index = self.runtime.current_split_index();
+            self.runtime.split();
+
+            // The timer ignores splits while it is paused, in which case the milestone
+            // may still be split later
+            if self.runtime.current_split_index() > split_index {
+                // The milestone is forgotten if there is no room left, which only
+                // allows a repeated split
+                _ = self.split_milestones.try_push((split_index, milestone));
+            }
+            self.observe_timer();
         }
 
-        if self.split() {
-            asr::timer::split();
+        if self.runtime.timer_state() == TimerState::NotRunning && self.start() {
+            self.runtime.start();
+            self.observe_timer();
         }
+    }
 
-        if asr::timer::state() == TimerState::NotRunning && self.start() {
-            asr::timer::start();
+    /// Reacts to changes of the timer, whether they were made by the auto splitter or
+    /// manually.
+    fn observe_timer(&mut self) {
+        let state = self.runtime.timer_state();
+        let split_index = self.runtime.current_split_index();
 
-            if asr::timer::state() == TimerState::Running {
-                // TODO: run this even if the timer is started manually
+        match (self.timer_previous_state, state) {
+            (TimerState::NotRunning, TimerState::Running | TimerState::Paused) => {
+                self.on_start();
+            }
+            (
+                TimerState::Running | TimerState::Paused | TimerState::Ended,
+                TimerState::NotRunning,
+            ) => self.on_reset(),
+            // Pausing or resuming the timer keeps the state of the run
+            _ => {
+                if let (Some(previous_split_index), Some(split_index)) =
+                    (self.timer_previous_split_index, split_index)
+                {
+                    if split_index > previous_split_index {
+                        self.on_split(split_index);
+                    } else if split_index < previous_split_index {
+                        self.on_undo_split(split_index);
+                    }
+                }
             }
         }
 
-        if self.timer_previous_state == TimerState::NotRunning
-            && asr::timer::state() == TimerState::Running
-        {
-            self.on_start();
+        if matches!(
+            (self.timer_previous_state, state),
+            (TimerState::Running | TimerState::Paused, TimerState::Ended)
+        ) {
+            self.on_end();
         }
 
-        self.timer_previous_state = asr::timer::state();
+        self.timer_previous_state = state;
+        self.timer_previous_split_index = split_index;
     }
 
     fn init(&mut self) {
-        Self::set_death_count_variable(self.process.death_count.current);
         self.death_count_offset = 0;
+        self.reset_split_death_count_offsets(self.process.death_count.current);
+        self.level_death_count_offset = self.process.death_count.current;
+        self.set_death_count_variables();
+        self.runtime.set_variable_int("deaths_previous_level", 0);
+
+        self.set_tick_rate();
 
-        Self::set_level_time_variable(0.0);
+        self.set_level_time_variable(0.0);
+        self.set_level_variables();
+        self.set_warp_zone_variables();
 
         // In 1.2.5 watching a replay still counts as playing (playing == 1), because of
         // that exiting to the map after completing the level doesn't split
@@ -84,14 +257,168 @@ impl<'settings> AutoSplitter<'settings> {
         self.level_time = Self::DUMMY_LEVEL_TIME;
     }
 
+    fn set_tick_rate(&mut self) {
+        self.tick_rate = self.settings.tick_rate;
+        self.runtime
+            .set_tick_rate(self.tick_rate.ticks_per_second());
+    }
+
+    fn report_read_failures(&mut self) {
+        let mut message = ArrayString::<256>::new();
+        write!(
+            message,
+            "Failed to read for {} ticks:",
+            Self::READ_FAILURE_REPORT_TICKS,
+        )
+        .unwrap();
+        let prefix_length = message.len();
+
+        if self
+            .process
+            .write_failures(&mut message, Self::READ_FAILURE_REPORT_TICKS)
+            .is_ok()
+            && message.len() > prefix_length
+        {
+            self.runtime.print_message(&message);
+        }
+    }
+
+    fn record_trace(&mut self) {
+        let mut line = ArrayString::<512>::new();
+        write!(
+            line,
+            "trace {} timer={:?}",
+            self.tick,
+            self.runtime.timer_state(),
+        )
+        .unwrap();
+        let prefix_length = line.len();
+
+        // Everything is written on the first recorded tick so that the trace can be
+        // replayed from there.
+        self.process
+            .write_changes(&mut line, !self.was_recording_trace)
+            .unwrap();
+
+        if line.len() > prefix_length {
+            self.runtime.print_message(&line);
+        }
+    }
+
     fn update(&mut self) {
-        // Update the death counter
-        if !self.is_death_counter_frozen() && self.process.death_count.increased() {
-            Self::set_death_count_variable(
-                self.process.death_count.current - self.death_count_offset,
-            );
+        // Update the death counters
+        let mut death_counts_changed = self.process.death_count.increased();
+
+        if self
+            .process
+            .known_game_state
+            .changed_to(&GameState::EnteringLevel)
+        {
+            self.level_death_count_offset = self.process.death_count.current;
+            death_counts_changed = true;
+        }
+
+        if !self.is_death_counter_frozen() {
+            if death_counts_changed {
+                self.set_death_count_variables();
+            }
+
+            if self.level_finished() || self.boss_finished() {
+                self.runtime.set_variable_int(
+                    "deaths_previous_level",
+                    self.process.death_count.current - self.level_death_count_offset,
+                );
+            }
         }
 
+        self.check_level_beaten();
+
+        // Update the level history
+        if self.level_finished()
+            && matches!(
+                self.runtime.timer_state(),
+                TimerState::Running | TimerState::Paused
+            )
+        {
+            let record = LevelRecord {
+                level: self.current_level(),
+                level_time: self.process.level_time.current,
+                deaths: self.process.death_count.current - self.level_death_count_offset,
+            };
+            // Levels beyond the capacity of the history are not recorded
+            _ = self.level_history.try_push(record);
+
+            let mut value = ArrayString::<96>::new();
+            if write!(
+                value,
+                "{}: {:.3} ({} deaths)",
+                record.level,
+                record.bounded_level_time(),
+                record.deaths,
+            )
+            .is_ok()
+            {
+                self.runtime.set_variable("last_level", &value);
+            }
+        }
+
+        // Update the current level display
+        if self.process.world.changed()
+            || self.process.level.changed()
+            || self.process.level_type.changed()
+        {
+            self.set_level_variables();
+        }
+
+        // Update the unlocked character count
+        if self.process.characters.changed() {
+            self.runtime
+                .set_variable_int("characters_unlocked", self.process.characters.current);
+        }
+
+        // Update the warp zone counters
+        if self.warp_zone_entered() {
+            self.warp_zones_entered += 1;
+            self.set_warp_zone_variables();
+        }
+        if self.warp_zone_completed() {
+            self.warp_zones_completed += 1;
+            self.set_warp_zone_variables();
+        }
+
+        self.update_level_time();
+    }
+
+    /// Reports level completions which were never followed by `level_beaten`
+    /// changing to 1 or by a transition to the map, which likely means that
+    /// `level_beaten` was only 1 between two ticks.
+    fn check_level_beaten(&mut self) {
+        if self.level_finished() && self.process.level_beaten.current == 0 {
+            self.unconfirmed_level_finish_tick = Some(self.tick - 1);
+        }
+
+        if self.process.level_beaten.changed_from_to(&0, &1)
+            || self.process.level_transition.changed_from_to(&0, &1)
+            || self.process.playing.current == 0
+        {
+            self.unconfirmed_level_finish_tick = None;
+        }
+
+        // The level time is reset when the next level is entered
+        if self.process.level_time.changed_to(&Self::DUMMY_LEVEL_TIME) {
+            if let Some(tick) = self.unconfirmed_level_finish_tick.take() {
+                let mut message = ArrayString::<96>::new();
+                write!(
+                    message,
+                    "Missed level_beaten changing to 1 for the level finished on tick {tick}",
+                )
+                .unwrap();
+                self.runtime.print_message(&message);
+            }
+        }
+    }
+
+    fn update_level_time(&mut self) {
         // Update the level time display. The level time stays at
         // `Self::DUMMY_LEVEL_TIME` while playing the level.
         if self
@@ -100,7 +427,21 @@ impl<'settings> AutoSplitter<'settings> {
             .bytes_changed_from(&Self::DUMMY_LEVEL_TIME)
         {
             // The timer glitch may cause the level time to be 0.0 here.
-            Self::set_level_time_variable(self.process.level_time.current);
+            self.set_level_time_variable(self.process.level_time.current);
+        }
+
+        // Update the sum of level times
+        if self
+            .process
+            .level_time
+            .bytes_changed_from(&Self::DUMMY_LEVEL_TIME)
+            && matches!(
+                self.runtime.timer_state(),
+                TimerState::Running | TimerState::Paused
+            )
+        {
+            self.level_time_sum += f64::from(self.process.level_time.current);
+            self.set_level_time_sum_variable();
         }
 
         // Update the level time
@@ -120,6 +461,50 @@ impl<'settings> AutoSplitter<'settings> {
         }
     }
 
+    fn update_game_time(&mut self) {
+        if !matches!(
+            self.runtime.timer_state(),
+            TimerState::Running | TimerState::Paused
+        ) {
+            return;
+        }
+
+        match self.settings.game_time_mode {
+            GameTimeMode::Disabled => {}
+            GameTimeMode::LevelTimeSum => {
+                // Game time only advances in whole level times, so it stays paused and
+                // is set to the new sum whenever a level is completed.
+                self.runtime.pause_game_time();
+
+                if self
+                    .process
+                    .level_time
+                    .bytes_changed_from(&Self::DUMMY_LEVEL_TIME)
+                    && self.is_level_time_sum_valid()
+                {
+                    self.runtime
+                        .set_game_time(Duration::seconds_f64(self.level_time_sum));
+                }
+            }
+            GameTimeMode::LoadsRemoved => {
+                if self.is_loading() {
+                    self.runtime.pause_game_time();
+                } else {
+                    self.runtime.resume_game_time();
+                }
+            }
+        }
+    }
+
+    /// Whether the game is loading a level or the chapter selection, or transitioning
+    /// between a level and the map.
+    fn is_loading(&self) -> bool {
+        matches!(
+            self.process.game_state.current,
+            GameState::EnteringLevel | GameState::EnteringChapterSelection
+        ) || self.process.level_transition.current != 0
+    }
+
     fn reset(&self) -> bool {
         if self.process.game_state.current == GameState::TitleScreen {
             return true;
@@ -131,54 +516,55 @@ impl<'settings> AutoSplitter<'settings> {
             return true;
         }
 
+        if self.settings.il_mode
+            && (self.process.death_count.increased()
+                || self
+                    .process
+                    .known_game_state
+                    .changed_to(&GameState::LevelSelection))
+        {
+            return true;
+        }
+
         false
     }
 
-    fn split(&self) -> bool {
+    /// The milestones reached on this tick which are enabled as splits.
+    fn reached_milestones(&self) -> ArrayVec<Milestone, 8> {
+        let current_level = Milestone::Level(self.current_level());
+        let mut milestones = ArrayVec::new();
+
+        if self.settings.il_mode {
+            if self.level_finished() || self.boss_finished() {
+                milestones.push(current_level);
+            }
+            return milestones;
+        }
+
         // Boss completion splits
-        if self.process.game_state.current == GameState::Playing
-            && self.process.not_in_cutscene.changed_from_to(&1, &0)
-            && (self.process.world.current != 6 || self.settings.split_after_level)
-            && self.process.level.current == 99
+        if self.boss_finished()
+            && (self.settings.split_after_level
+                || self.level_splits.is_enabled(Level::Boss {
+                    world: self.process.world.current,
+                }))
         {
-            return true;
+            milestones.push(current_level);
         }
 
         // Final cutscene splits
         if self.process.fetus.changed_to(&0x8000_0000)
-            && (!(self.process.level_type.current == 0 && self.settings.dark_ending)
+            && (!(self.process.level_type.current == 0 && self.settings.dark_ending())
                 || self.settings.split_after_level)
         {
-            return true;
+            milestones.push(Milestone::FinalCutscene);
         }
 
         // IL splits
-        if self.settings.split_after_level {
-            if self.process.level_beaten.changed_from_to(&0, &1) {
-                return true;
-            }
-
-            if self.process.level_transition.changed_from_to(&0, &1)
-                && self.process.game_state.current == GameState::Playing
-                && (self.level_time != Self::DUMMY_LEVEL_TIME || self.process.playing.old == 0)
-            {
-                return true;
-            }
-
-            if self.process.game_state.current == GameState::Playing
-                && ([0, 1].contains(&self.process.level_type.old))
-                && (2..=5).contains(&self.process.level_type.current)
-            {
-                return true;
-            }
-
-            if ([0, 1].contains(&self.process.level_type.current))
-                && ((self.process.level_type.old >= 2 && self.process.level.old == 2)
-                    || (self.process.level_type.old == 6 && self.process.level.old == 0))
-                && self.process.level_time.current != Self::DUMMY_LEVEL_TIME
-            {
-                return true;
-            }
+        if let Some(level) = self
+            .completed_level()
+            .filter(|&level| self.settings.split_after_level || self.level_splits.is_enabled(level))
+        {
+            milestones.push(Milestone::Level(level));
         }
 
         // Boss entrance split
@@ -186,45 +572,136 @@ impl<'settings> AutoSplitter<'settings> {
             && self.process.game_state.current == GameState::EnteringLevel
             && self.process.in_special_level.changed_from_to(&0, &1)
         {
-            return true;
+            milestones.push(Milestone::BossEntrance {
+                world: self.process.world.current,
+            });
+        }
+
+        // Character unlock split. Characters are unlocked one at a time while playing,
+        // while loading a save file can increase their number by any amount.
+        if self.settings.split_on_character_unlock
+            && self.process.game_state.current == GameState::Playing
+            && self.process.characters.old.checked_add(1) == Some(self.process.characters.current)
+        {
+            milestones.push(Milestone::CharacterUnlock {
+                character_count: self.process.characters.current,
+            });
         }
 
         // IW ending split
         if self.settings.iw_mode
             && ((self.process.world.current == 6 && self.process.level.current == 4)
                 || self.process.level.current == 19)
-            && self.process.playing.old == 1
-            && self.process.level_time.old == Self::DUMMY_LEVEL_TIME
-            && self.process.level_time.current != Self::DUMMY_LEVEL_TIME
+            && self.level_finished()
         {
-            return true;
+            milestones.push(current_level);
         }
 
         // Dark Ending splits
-        if self.settings.dark_ending
+        if self.settings.dark_ending()
             && !self.settings.split_after_level
-            && self.process.game_state.changed_from_to(
+            && self.process.known_game_state.changed_from_to(
                 &GameState::Playing,
                 &GameState::LevelSelectionWithBossUnlocking,
             )
             && (1..=5).contains(&self.process.world.current)
         {
-            return true;
+            milestones.push(Milestone::BossUnlock {
+                world: self.process.world.current,
+            });
         }
 
-        false
+        milestones
+    }
+
+    /// Whether the milestone already caused a split in this run and may not split again.
+    fn is_split_suppressed(&self, milestone: Milestone) -> bool {
+        !self.settings.allow_repeated_splits
+            && !milestone.is_repeatable()
+            && self
+                .split_milestones
+                .iter()
+                .any(|&(_, split_milestone)| split_milestone == milestone)
+    }
+
+    /// Whether a level was just beaten, which is when its level time is shown.
+    fn level_finished(&self) -> bool {
+        self.process.playing.old == 1
+            && self.process.level_time.old == Self::DUMMY_LEVEL_TIME
+            && self.process.level_time.current != Self::DUMMY_LEVEL_TIME
+    }
+
+    fn boss_finished(&self) -> bool {
+        self.process.game_state.current == GameState::Playing
+            && self.process.not_in_cutscene.changed_from_to(&1, &0)
+            && self.process.level.current == Level::BOSS_INDEX
+    }
+
+    fn current_level(&self) -> Level {
+        Level::new(
+            self.process.world.current,
+            self.process.level.current,
+            self.process.level_type.current,
+        )
+    }
+
+    fn completed_level(&self) -> Option<Level> {
+        let current_level = self.current_level();
+        let warp_zone = Level::WarpZone {
+            world: self.process.world.current,
+        };
+
+        if self.process.level_beaten.changed_from_to(&0, &1) {
+            return Some(current_level);
+        }
+
+        if self.process.level_transition.changed_from_to(&0, &1)
+            && self.process.game_state.current == GameState::Playing
+            && (self.level_time != Self::DUMMY_LEVEL_TIME || self.process.playing.old == 0)
+        {
+            return Some(current_level);
+        }
+
+        if self.warp_zone_entered() || self.warp_zone_completed() {
+            return Some(warp_zone);
+        }
+
+        None
+    }
+
+    fn warp_zone_entered(&self) -> bool {
+        self.process.game_state.current == GameState::Playing
+            && ([0, 1].contains(&self.process.level_type.old))
+            && (2..=5).contains(&self.process.level_type.current)
+    }
+
+    fn warp_zone_completed(&self) -> bool {
+        ([0, 1].contains(&self.process.level_type.current))
+            && ((self.process.level_type.old >= 2 && self.process.level.old == 2)
+                || (self.process.level_type.old == 6 && self.process.level.old == 0))
+            && self.process.level_time.current != Self::DUMMY_LEVEL_TIME
     }
 
     fn start(&self) -> bool {
+        if self.settings.il_mode {
+            return self
+                .process
+                .known_game_state
+                .changed_from_to(&GameState::EnteringLevel, &GameState::Playing)
+                || (self.process.game_state.current == GameState::Playing
+                    && self.process.death_count.increased());
+        }
+
         if !self.settings.iw_mode
             && self.process.game_state.current == GameState::EnteringChapterSelection
+            && (!self.settings.start_only_on_new_save_file() || self.is_new_save_file())
         {
             return true;
         }
 
         if self.settings.iw_mode
             && ((self.process.characters.current != 1
-                && self.process.game_state.changed_from_to(
+                && self.process.known_game_state.changed_from_to(
                     &GameState::CharacterSelection,
                     &GameState::CharacterSelectionWithCharacterSelected,
                 ))
@@ -232,7 +709,7 @@ impl<'settings> AutoSplitter<'settings> {
                     || ([6, 7].contains(&self.process.world.current)))
                     && self
                         .process
-                        .game_state
+                        .known_game_state
                         .changed_from_to(&GameState::LevelSelection, &GameState::EnteringLevel)))
             && (self.process.level.current == 0 || !self.settings.iw_mode_split_on_first_level)
         {
@@ -242,20 +719,218 @@ impl<'settings> AutoSplitter<'settings> {
         false
     }
 
+    /// Whether the loaded save file has not been played yet. The chosen save slot
+    /// itself is not known.
+    fn is_new_save_file(&self) -> bool {
+        self.process.death_count.current == 0 && self.process.characters.current <= 1
+    }
+
     fn on_start(&mut self) {
         self.death_count_offset = self.process.death_count.old;
-        Self::set_death_count_variable(self.process.death_count.current - self.death_count_offset);
+        self.reset_split_death_count_offsets(self.death_count_offset);
+        self.set_death_count_variables();
+
+        self.level_time_sum = 0.0;
+        self.set_level_time_sum_variable();
+        self.best_level_time_sum = if self.is_full_game_run() {
+            self.runtime
+                .load_setting_f64(&self.best_level_time_sum_key())
+                .filter(|best| Self::LEVEL_TIME_SUM_RANGE.contains(best))
+        } else {
+            None
+        };
+        self.runtime.set_variable("level_time_sum_delta", "-");
+        self.split_milestones.clear();
+        self.level_history.clear();
+
+        self.reset_warp_zone_counters();
+        match self.settings.game_time_mode {
+            GameTimeMode::Disabled => {}
+            GameTimeMode::LevelTimeSum => {
+                self.runtime.pause_game_time();
+                self.runtime.set_game_time(Duration::ZERO);
+            }
+            GameTimeMode::LoadsRemoved => {
+                self.runtime.set_game_time(Duration::ZERO);
+                self.update_game_time();
+            }
+        }
+    }
+
+    fn on_split(&mut self, split_index: u64) {
+        // Skipped splits start with the same death count as the new split
+        let split_count = usize::try_from(split_index).map_or(usize::MAX, |index| index + 1);
+        let missing = split_count
+            .saturating_sub(self.split_death_count_offsets.len())
+            .min(self.split_death_count_offsets.remaining_capacity());
+        self.split_death_count_offsets
+            .extend(iter::repeat_n(self.process.death_count.current, missing));
+        if !self.is_death_counter_frozen() {
+            self.set_death_count_variables();
+        }
+    }
+
+    fn on_end(&mut self) {
+        self.log_level_history();
+
+        // Sums of single worlds or levels are not comparable with each other
+        if self.is_full_game_run() && self.is_level_time_sum_valid() {
+            let mut delta = ArrayString::<32>::new();
+            if let Some(best) = self.best_level_time_sum {
+                if write!(delta, "{:+.2}", self.level_time_sum - best).is_err() {
+                    delta.clear();
+                }
+            }
+            if delta.is_empty() {
+                delta.push('-');
+            }
+            self.runtime.set_variable("level_time_sum_delta", &delta);
+
+            if self
+                .best_level_time_sum
+                .is_none_or(|best| self.level_time_sum < best)
+            {
+                self.runtime
+                    .store_setting_f64(&self.best_level_time_sum_key(), self.level_time_sum);
+            }
+        }
+    }
+
+    /// Logs the levels completed during the run as compact `level_history` lines and
+    /// forgets them.
+    fn log_level_history(&mut self) {
+        const PREFIX: &str = "level_history";
+
+        let mut line = ArrayString::<256>::new();
+        line.push_str(PREFIX);
+        for record in self.level_history.drain(..) {
+            let mut entry = ArrayString::<64>::new();
+            if write!(
+                entry,
+                " {}:{:.3}:{}",
+                record.level.short_name(),
+                record.bounded_level_time(),
+                record.deaths,
+            )
+            .is_err()
+            {
+                continue;
+            }
+
+            if line.try_push_str(&entry).is_err() {
+                self.runtime.print_message(&line);
+                line.clear();
+                line.push_str(PREFIX);
+                line.push_str(&entry);
+            }
+        }
+
+        if line.len() > PREFIX.len() {
+            self.runtime.print_message(&line);
+        }
+    }
+
+    /// Whether the sum of level times can be used as game time and stored as a best.
+    /// Level times read from garbage memory can make it NaN or arbitrarily large.
+    fn is_level_time_sum_valid(&self) -> bool {
+        Self::LEVEL_TIME_SUM_RANGE.contains(&self.level_time_sum)
+    }
+
+    /// Whether the run covers the whole game rather than a single world or level.
+    fn is_full_game_run(&self) -> bool {
+        !self.settings.iw_mode && !self.settings.il_mode
+    }
+
+    fn best_level_time_sum_key(&self) -> ArrayString<64> {
+        let mut key = ArrayString::new();
+        write!(key, "best_level_time_sum_{}", self.settings.category.key()).unwrap();
+        key
+    }
+
+    fn on_undo_split(&mut self, split_index: u64) {
+        let split_count = usize::try_from(split_index).map_or(usize::MAX, |index| index + 1);
+        self.split_death_count_offsets.truncate(split_count);
+        self.set_death_count_variables();
+
+        self.split_milestones
+            .retain(|&mut (index, _)| index.is_none_or(|index| index < split_index));
+    }
+
+    fn on_reset(&mut self) {
+        let death_count = self.process.death_count.current;
+        self.death_count_offset = death_count;
+        self.reset_split_death_count_offsets(death_count);
+        self.set_death_count_variables();
+
+        self.level_time_sum = 0.0;
+        self.reset_warp_zone_counters();
+        // The history of a finished run has already been logged
+        self.log_level_history();
+
+        if let Some((settings, level_splits)) = self.pending_settings.take() {
+            *self.settings = settings;
+            *self.level_splits = level_splits;
+        }
     }
 
     fn is_death_counter_frozen(&self) -> bool {
-        self.settings.freeze_death_counter_on_finish && asr::timer::state() == TimerState::Ended
+        self.settings.freeze_death_counter_on_finish
+            && self.runtime.timer_state() == TimerState::Ended
+    }
+
+    fn reset_split_death_count_offsets(&mut self, death_count: i32) {
+        self.split_death_count_offsets.clear();
+        self.split_death_count_offsets.push(death_count);
+    }
+
+    fn set_death_count_variables(&mut self) {
+        let death_count = self.process.death_count.current;
+        let split_death_count_offset = self
+            .split_death_count_offsets
+            .last()
+            .copied()
+            .unwrap_or(self.death_count_offset);
+        self.runtime
+            .set_variable_int("deaths", death_count - self.death_count_offset);
+        self.runtime
+            .set_variable_int("deaths_split", death_count - split_death_count_offset);
+        self.runtime
+            .set_variable_int("deaths_level", death_count - self.level_death_count_offset);
+    }
+
+    fn set_level_variables(&mut self) {
+        let world = self.process.world.current;
+        let level = self.current_level();
+
+        let mut value = ArrayString::<64>::new();
+        write!(value, "{level}").unwrap();
+        self.runtime.set_variable("level", &value);
+
+        value.clear();
+        write!(value, "{world} {}", Level::world_name(world)).unwrap();
+        self.runtime.set_variable("world", &value);
+    }
+
+    fn reset_warp_zone_counters(&mut self) {
+        self.warp_zones_entered = 0;
+        self.warp_zones_completed = 0;
+        self.set_warp_zone_variables();
+    }
+
+    fn set_warp_zone_variables(&mut self) {
+        self.runtime
+            .set_variable_int("warp_zones_entered", self.warp_zones_entered);
+        self.runtime
+            .set_variable_int("warp_zones_completed", self.warp_zones_completed);
     }
 
-    fn set_death_count_variable(value: impl asr::itoa::Integer) {
-        asr::timer::set_variable_int("deaths", value);
+    fn set_level_time_variable(&mut self, value: f32) {
+        self.runtime.set_variable_float("level_time", value);
     }
 
-    fn set_level_time_variable(value: impl asr::ryu::Float) {
-        asr::timer::set_variable_float("level_time", value);
+    #[expect(clippy::cast_possible_truncation)]
+    fn set_level_time_sum_variable(&mut self) {
+        self.runtime
+            .set_variable_float("level_time_sum", self.level_time_sum as f32);
     }
 }
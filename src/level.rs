@@ -0,0 +1,122 @@
+use core::fmt;
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub(crate) enum Side {
+    Light,
+    Dark,
+}
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub(crate) enum Level {
+    Normal { world: u8, index: u8, side: Side },
+    Boss { world: u8 },
+    WarpZone { world: u8 },
+}
+
+impl Level {
+    pub(crate) const WORLD_COUNT: u8 = 7;
+    pub(crate) const BOSS_INDEX: u8 = 99;
+
+    /// Identifies a level from the values of the `world`, `level` and `level_type`
+    /// game variables.
+    pub(crate) fn new(world: u8, index: u8, level_type: i32) -> Self {
+        match (index, level_type) {
+            (Self::BOSS_INDEX, _) => Self::Boss { world },
+            (_, 0) => Self::Normal {
+                world,
+                index,
+                side: Side::Light,
+            },
+            (_, 1) => Self::Normal {
+                world,
+                index,
+                side: Side::Dark,
+            },
+            _ => Self::WarpZone { world },
+        }
+    }
+
+    pub(crate) fn world(self) -> u8 {
+        match self {
+            Self::Normal { world, .. } | Self::Boss { world } | Self::WarpZone { world } => world,
+        }
+    }
+
+    /// All levels of a world, light levels first, followed by dark levels, the boss
+    /// and the warp zone, if the world has them.
+    pub(crate) fn all_in_world(world: u8) -> impl Iterator<Item = Self> {
+        let normal_level_count = match world {
+            1..=5 | 7 => 20,
+            6 => 5,
+            _ => 0,
+        };
+
+        [Side::Light, Side::Dark]
+            .into_iter()
+            .flat_map(move |side| {
+                (0..normal_level_count).map(move |index| Self::Normal { world, index, side })
+            })
+            .chain((1..=6).contains(&world).then_some(Self::Boss { world }))
+            .chain((1..=5).contains(&world).then_some(Self::WarpZone { world }))
+    }
+
+    /// Formats the level the way it is usually referred to, such as `1-1`, `1-1x` or
+    /// `1-Boss`.
+    pub(crate) fn short_name(self) -> impl fmt::Display {
+        ShortName(self)
+    }
+
+    pub(crate) fn world_name(world: u8) -> &'static str {
+        match world {
+            1 => "The Forest",
+            2 => "The Hospital",
+            3 => "The Salt Factory",
+            4 => "Hell",
+            5 => "Rapture",
+            6 => "The End",
+            7 => "Cotton Alley",
+            _ => "Unknown",
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let world_name = Self::world_name(self.world());
+        match *self {
+            Self::Normal {
+                world,
+                index,
+                side: Side::Light,
+            } => write!(f, "{world}-{} {world_name} (Light)", u16::from(index) + 1),
+            Self::Normal {
+                world,
+                index,
+                side: Side::Dark,
+            } => write!(f, "{world}-{} {world_name} (Dark)", u16::from(index) + 1),
+            Self::Boss { world } => write!(f, "{world}-Boss {world_name}"),
+            Self::WarpZone { world } => write!(f, "{world}-Warp Zone {world_name}"),
+        }
+    }
+}
+
+struct ShortName(Level);
+
+impl fmt::Display for ShortName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Level::Normal {
+                world,
+                index,
+                side: Side::Light,
+            } => write!(f, "{world}-{}", u16::from(index) + 1),
+            Level::Normal {
+                world,
+                index,
+                side: Side::Dark,
+            } => write!(f, "{world}-{}x", u16::from(index) + 1),
+            Level::Boss { world } => write!(f, "{world}-Boss"),
+            Level::WarpZone { world } => write!(f, "{world}-Warp"),
+        }
+    }
+}
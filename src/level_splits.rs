@@ -0,0 +1,142 @@
+use crate::level::{Level, Side};
+use arrayvec::ArrayString;
+use asr::settings::{gui, Map};
+use core::fmt::Write;
+
+/// Settings tree which allows choosing individual level completions to split on.
+#[derive(Clone)]
+pub(crate) struct LevelSplits {
+    enabled: [u64; Level::WORLD_COUNT as usize],
+}
+
+impl LevelSplits {
+    const DARK_BIT_OFFSET: u32 = 20;
+    const BOSS_BIT: u32 = 40;
+    const WARP_ZONE_BIT: u32 = 41;
+
+    /// Creates the settings with their default values, without registering them.
+    pub(crate) fn new() -> Self {
+        let mut this = Self {
+            enabled: [0; Level::WORLD_COUNT as usize],
+        };
+        for world in 1..=Level::WORLD_COUNT {
+            for level in Level::all_in_world(world) {
+                this.set_enabled(level, Self::default_value(level));
+            }
+        }
+        this
+    }
+
+    pub(crate) fn register() -> Self {
+        let mut this = Self::new();
+
+        gui::add_title("level_splits", "Split after specific levels", 0);
+        for world in 1..=Level::WORLD_COUNT {
+            let mut key = ArrayString::<32>::new();
+            let mut description = ArrayString::<32>::new();
+            write!(key, "level_splits_{world}").unwrap();
+            write!(description, "{world} - {}", Level::world_name(world)).unwrap();
+            gui::add_title(&key, &description, 1);
+
+            for level in Level::all_in_world(world) {
+                let value = gui::add_bool(
+                    &Self::key(level),
+                    &Self::description(level),
+                    Self::default_value(level),
+                );
+                this.set_enabled(level, value);
+            }
+        }
+
+        this
+    }
+
+    pub(crate) fn update_from(&mut self, settings_map: &Map) {
+        for world in 1..=Level::WORLD_COUNT {
+            for level in Level::all_in_world(world) {
+                let value = settings_map
+                    .get(&Self::key(level))
+                    .and_then(|value| value.get_bool())
+                    .unwrap_or_else(|| Self::default_value(level));
+                self.set_enabled(level, value);
+            }
+        }
+    }
+
+    pub(crate) fn is_enabled(&self, level: Level) -> bool {
+        let Some(bit) = Self::bit(level) else {
+            return false;
+        };
+
+        self.enabled
+            .get(usize::from(level.world()).wrapping_sub(1))
+            .is_some_and(|bits| bits & (1 << bit) != 0)
+    }
+
+    pub(crate) fn set_enabled(&mut self, level: Level, value: bool) {
+        let (Some(bits), Some(bit)) = (
+            self.enabled
+                .get_mut(usize::from(level.world()).wrapping_sub(1)),
+            Self::bit(level),
+        ) else {
+            return;
+        };
+
+        if value {
+            *bits |= 1 << bit;
+        } else {
+            *bits &= !(1 << bit);
+        }
+    }
+
+    /// The bit of the level within the bits of its world. Levels with an index read
+    /// from the game which is out of range have no bit, and are never enabled.
+    fn bit(level: Level) -> Option<u32> {
+        match level {
+            Level::Normal { index, side, .. } => {
+                let index = u32::from(index);
+                (index < Self::DARK_BIT_OFFSET).then_some(match side {
+                    Side::Light => index,
+                    Side::Dark => Self::DARK_BIT_OFFSET + index,
+                })
+            }
+            Level::Boss { .. } => Some(Self::BOSS_BIT),
+            Level::WarpZone { .. } => Some(Self::WARP_ZONE_BIT),
+        }
+    }
+
+    fn default_value(level: Level) -> bool {
+        // Completing the bosses of the first five worlds has always split
+        matches!(level, Level::Boss { world: 1..=5 })
+    }
+
+    fn key(level: Level) -> ArrayString<32> {
+        let mut key = ArrayString::new();
+        match level {
+            Level::Normal {
+                world,
+                index,
+                side: Side::Light,
+            } => write!(key, "split_level_{world}_{}", u16::from(index) + 1),
+            Level::Normal {
+                world,
+                index,
+                side: Side::Dark,
+            } => write!(key, "split_level_{world}_{}_dark", u16::from(index) + 1),
+            Level::Boss { world } => write!(key, "split_boss_{world}"),
+            Level::WarpZone { world } => write!(key, "split_warp_zone_{world}"),
+        }
+        .unwrap();
+        key
+    }
+
+    fn description(level: Level) -> ArrayString<32> {
+        let mut description = ArrayString::new();
+        match level {
+            Level::WarpZone { world } => write!(description, "{world} warp zones"),
+            _ => write!(description, "{}", level.short_name()),
+        }
+        .unwrap();
+        description
+    }
+}
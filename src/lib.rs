@@ -1,20 +1,46 @@
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 
 mod auto_splitter;
+mod level;
+mod level_splits;
+mod runtime;
 mod smb_process;
+#[cfg(test)]
+mod tests;
 
-use crate::{auto_splitter::AutoSplitter, smb_process::SmbProcess};
-use asr::{future::next_tick, settings::Gui, Error};
+use crate::{
+    auto_splitter::AutoSplitter,
+    level_splits::LevelSplits,
+    runtime::{update_settings_map, AsrRuntime},
+    smb_process::{AttachError, SmbProcess},
+};
+use arrayvec::ArrayString;
+use asr::{
+    future::next_tick,
+    settings::{Gui, Map, Value},
+    Error,
+};
+use core::fmt::Write;
 
+#[cfg(not(test))]
 asr::async_main!(stable);
+#[cfg(not(test))]
 asr::panic_handler!();
 
-#[derive(Gui)]
+#[derive(Gui, Clone)]
 struct Settings {
+    /// Category - chooses the default save file and ending, which can be overridden
+    /// by the settings below. Categories with the same rules share a choice.
+    category: Category,
+
     /// Reset on the main menu
     #[default = false]
     reset_on_main_menu: bool,
 
+    /// Start full game runs on - the chosen save file can't be read, so a save file
+    /// counts as new if it has no deaths and at most one unlocked character
+    start_save_file: StartSaveFile,
+
     /// Split after every level
     #[default = false]
     split_after_level: bool,
@@ -27,9 +53,13 @@ struct Settings {
     #[default = true]
     iw_mode_split_on_first_level: bool,
 
-    /// Dark ending mode
-    #[default = true]
-    dark_ending: bool,
+    /// IL mode - start when entering a level, split when beating it, and reset on
+    /// death or when leaving the level
+    #[default = false]
+    il_mode: bool,
+
+    /// Ending
+    ending: Ending,
 
     /// Split when entering boss 1
     #[default = false]
@@ -55,12 +85,155 @@ struct Settings {
     #[default = false]
     split_before_boss_6: bool,
 
+    /// Split when a character is unlocked
+    #[default = false]
+    split_on_character_unlock: bool,
+
+    /// Allow splitting again on levels and events which already caused a split
+    #[default = false]
+    allow_repeated_splits: bool,
+
     /// Freeze the death counter when the run ends
     #[default = false]
     freeze_death_counter_on_finish: bool,
+
+    /// Game time
+    game_time_mode: GameTimeMode,
+
+    /// Tick rate - higher rates make it less likely that changes lasting only a
+    /// single frame are missed
+    tick_rate: TickRate,
+
+    /// Log changes of game variables (for bug reports)
+    #[default = false]
+    record_trace: bool,
+}
+
+#[derive(Gui, Clone, Copy, Eq, PartialEq)]
+enum Category {
+    /// Custom or All Dark World - any save file, dark ending
+    #[default]
+    Custom,
+
+    /// Any%, Any% Glitchless or All Bandages - new save file, light ending
+    LightEnding,
+
+    /// 106% or 100% - new save file, dark ending
+    DarkEnding,
+}
+
+#[derive(Gui, Clone, Copy, Eq, PartialEq)]
+enum StartSaveFile {
+    /// Category default
+    #[default]
+    CategoryDefault,
+
+    /// Any save file
+    Any,
+
+    /// New save files only
+    New,
+}
+
+#[derive(Gui, Clone, Copy, Eq, PartialEq)]
+enum Ending {
+    /// Category default
+    #[default]
+    CategoryDefault,
+
+    /// Light ending
+    Light,
+
+    /// Dark ending - the final cutscene only splits on the dark side, and unlocking
+    /// a boss on the dark side splits
+    Dark,
+}
+
+#[derive(Gui, Clone, Copy, Eq, PartialEq)]
+enum GameTimeMode {
+    /// Disabled
+    #[default]
+    Disabled,
+
+    /// Sum of in-game level times
+    LevelTimeSum,
+
+    /// Real time without loads
+    LoadsRemoved,
+}
+
+#[derive(Gui, Clone, Copy, Eq, PartialEq)]
+enum TickRate {
+    /// 60 Hz
+    Hz60,
+
+    /// 120 Hz
+    #[default]
+    Hz120,
+
+    /// 240 Hz
+    Hz240,
+}
+
+impl TickRate {
+    fn ticks_per_second(self) -> f64 {
+        match self {
+            Self::Hz60 => 60.0,
+            Self::Hz120 => 120.0,
+            Self::Hz240 => 240.0,
+        }
+    }
+}
+
+impl Category {
+    /// Whether runs of the category start on a new save file by default.
+    fn starts_on_new_save_file(self) -> bool {
+        // All Dark World runs start on a save file with the light world completed
+        self != Self::Custom
+    }
+
+    /// Whether runs of the category end with the dark ending by default.
+    fn has_dark_ending(self) -> bool {
+        self != Self::LightEnding
+    }
+
+    /// The name identifying the category in the settings map.
+    fn key(self) -> &'static str {
+        match self {
+            Self::Custom => "custom",
+            Self::LightEnding => "light_ending",
+            Self::DarkEnding => "dark_ending",
+        }
+    }
 }
 
 impl Settings {
+    /// Takes over the settings which don't affect when the timer is started, split or
+    /// reset, and can therefore be changed during a run.
+    pub(crate) fn update_live_settings_from(&mut self, other: &Self) {
+        self.freeze_death_counter_on_finish = other.freeze_death_counter_on_finish;
+        self.tick_rate = other.tick_rate;
+        self.record_trace = other.record_trace;
+    }
+
+    pub(crate) fn start_only_on_new_save_file(&self) -> bool {
+        match self.start_save_file {
+            StartSaveFile::CategoryDefault => self.category.starts_on_new_save_file(),
+            StartSaveFile::Any => false,
+            StartSaveFile::New => true,
+        }
+    }
+
+    /// Whether the run ends with the dark ending, in which case the final cutscene
+    /// only splits on the dark side.
+    pub(crate) fn dark_ending(&self) -> bool {
+        match self.ending {
+            Ending::CategoryDefault => self.category.has_dark_ending(),
+            Ending::Light => false,
+            Ending::Dark => true,
+        }
+    }
+
     pub(crate) fn split_before_boss(&self, boss_index: u8) -> bool {
         match boss_index {
             1 => self.split_before_boss_1,
@@ -79,15 +252,57 @@ async fn main() {
 }
 
 async fn inner_main() -> Result<(), Error> {
+    migrate_dark_ending_setting();
     let mut settings = Settings::register();
+    let mut level_splits = LevelSplits::register();
 
     loop {
-        let process = asr::future::retry(SmbProcess::try_attach).await;
+        let mut last_attach_error = None;
+        let process = asr::future::retry(|| {
+            SmbProcess::try_attach()
+                .map_err(|error| {
+                    if last_attach_error != Some(error) {
+                        report_attach_error(error);
+                        last_attach_error = Some(error);
+                    }
+                })
+                .ok()
+        })
+        .await;
+        asr::timer::set_variable("attach_status", "Attached");
+        asr::timer::set_variable("game_version", process.version().name());
 
-        let mut auto_splitter = AutoSplitter::new(process, &mut settings);
+        let mut auto_splitter =
+            AutoSplitter::new(process, AsrRuntime, &mut settings, &mut level_splits);
         while auto_splitter.is_process_running() {
+            auto_splitter.update_settings();
             auto_splitter.run_tick();
             next_tick().await;
         }
     }
 }
+
+/// Carries the "Dark ending mode" setting of earlier versions over to the ending
+/// setting which replaced it, so that the ending of existing users doesn't change.
+fn migrate_dark_ending_setting() {
+    let settings_map = Map::load();
+    if settings_map.get("ending").is_some() {
+        return;
+    }
+    let Some(dark_ending) = settings_map
+        .get("dark_ending")
+        .and_then(|value| value.get_bool())
+    else {
+        return;
+    };
+
+    let ending = if dark_ending { "Dark" } else { "Light" };
+    update_settings_map(|settings_map| settings_map.insert("ending", &Value::from(ending)));
+}
+
+fn report_attach_error(error: AttachError) {
+    let mut message = ArrayString::<96>::new();
+    write!(message, "{error}").unwrap();
+    asr::print_message(&message);
+    asr::timer::set_variable("attach_status", &message);
+}
@@ -0,0 +1,112 @@
+use crate::{level_splits::LevelSplits, Settings};
+use asr::{
+    settings::{Gui, Map, Value},
+    time::Duration,
+    timer::TimerState,
+};
+
+/// The parts of the auto splitting runtime used by [`AutoSplitter`].
+///
+/// [`AutoSplitter`]: crate::auto_splitter::AutoSplitter
+pub(crate) trait Runtime {
+    fn timer_state(&self) -> TimerState;
+    fn current_split_index(&self) -> Option<u64>;
+    fn start(&mut self);
+    fn split(&mut self);
+    fn reset(&mut self);
+    fn pause_game_time(&mut self);
+    fn resume_game_time(&mut self);
+    fn set_game_time(&mut self, time: Duration);
+    fn set_variable(&mut self, key: &str, value: &str);
+    fn set_variable_int(&mut self, key: &str, value: i32);
+    fn set_variable_float(&mut self, key: &str, value: f32);
+    fn print_message(&mut self, message: &str);
+    fn set_tick_rate(&mut self, ticks_per_second: f64);
+    fn load_settings(&mut self, settings: &mut Settings, level_splits: &mut LevelSplits);
+    fn load_setting_f64(&self, key: &str) -> Option<f64>;
+    fn store_setting_f64(&mut self, key: &str, value: f64);
+}
+
+/// The runtime the auto splitter is actually executed in.
+pub(crate) struct AsrRuntime;
+
+impl Runtime for AsrRuntime {
+    fn timer_state(&self) -> TimerState {
+        asr::timer::state()
+    }
+
+    fn current_split_index(&self) -> Option<u64> {
+        asr::timer::current_split_index()
+    }
+
+    fn start(&mut self) {
+        asr::timer::start();
+    }
+
+    fn split(&mut self) {
+        asr::timer::split();
+    }
+
+    fn reset(&mut self) {
+        asr::timer::reset();
+    }
+
+    fn pause_game_time(&mut self) {
+        asr::timer::pause_game_time();
+    }
+
+    fn resume_game_time(&mut self) {
+        asr::timer::resume_game_time();
+    }
+
+    fn set_game_time(&mut self, time: Duration) {
+        asr::timer::set_game_time(time);
+    }
+
+    fn set_variable(&mut self, key: &str, value: &str) {
+        asr::timer::set_variable(key, value);
+    }
+
+    fn set_variable_int(&mut self, key: &str, value: i32) {
+        asr::timer::set_variable_int(key, value);
+    }
+
+    fn set_variable_float(&mut self, key: &str, value: f32) {
+        asr::timer::set_variable_float(key, value);
+    }
+
+    fn print_message(&mut self, message: &str) {
+        asr::print_message(message);
+    }
+
+    fn set_tick_rate(&mut self, ticks_per_second: f64) {
+        asr::set_tick_rate(ticks_per_second);
+    }
+
+    fn load_settings(&mut self, settings: &mut Settings, level_splits: &mut LevelSplits) {
+        let settings_map = Map::load();
+        settings.update_from(&settings_map);
+        level_splits.update_from(&settings_map);
+    }
+
+    fn load_setting_f64(&self, key: &str) -> Option<f64> {
+        Map::load().get(key)?.get_f64()
+    }
+
+    fn store_setting_f64(&mut self, key: &str, value: f64) {
+        update_settings_map(|map| map.insert(key, &Value::from(value)));
+    }
+}
+
+/// Changes the stored settings map. The change is retried if the settings were
+/// changed at the same time, such as by the user, so that neither change is lost.
+pub(crate) fn update_settings_map(change: impl Fn(&Map)) {
+    loop {
+        let old_map = Map::load();
+        let new_map = old_map.clone();
+        change(&new_map);
+        if new_map.store_if_unchanged(&old_map) {
+            break;
+        }
+    }
+}
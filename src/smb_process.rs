@@ -1,8 +1,63 @@
 use asr::{deep_pointer::DeepPointer, watcher::Pair, Error, PointerSize, Process};
+use core::fmt;
 
-pub(crate) struct SmbProcess {
-    process: Process,
-    pointer_paths: PointerPaths,
+/// A source of the game variables read by [`SmbProcess`].
+pub(crate) trait Memory {
+    fn is_open(&self) -> bool;
+
+    /// Reads the game variables for the current tick. Variables which could not be
+    /// read are `None`.
+    fn read_values(&mut self) -> Values;
+}
+
+#[derive(Clone, Copy, Default)]
+pub(crate) struct Values {
+    pub playing: Option<u8>,
+    pub level_time: Option<f32>,
+    pub world: Option<u8>,
+    pub not_in_cutscene: Option<u8>,
+    pub in_special_level: Option<u8>,
+    pub level_beaten: Option<u8>,
+    pub death_count: Option<i32>,
+    pub characters: Option<i32>,
+    pub level: Option<u8>,
+    pub game_state: Option<GameState>,
+    pub level_transition: Option<u8>,
+    pub fetus: Option<u32>,
+    pub level_type: Option<i32>,
+}
+
+#[derive(Clone, Copy, Default, Eq, PartialEq)]
+pub(crate) enum ReadStatus {
+    #[default]
+    Ok,
+    /// The variable was read after failing to be read on the previous tick.
+    Recovered,
+    Failed {
+        ticks: u32,
+    },
+}
+
+#[derive(Default)]
+pub(crate) struct ReadStatuses {
+    pub playing: ReadStatus,
+    pub level_time: ReadStatus,
+    pub world: ReadStatus,
+    pub not_in_cutscene: ReadStatus,
+    pub in_special_level: ReadStatus,
+    pub level_beaten: ReadStatus,
+    pub death_count: ReadStatus,
+    pub characters: ReadStatus,
+    pub level: ReadStatus,
+    pub game_state: ReadStatus,
+    pub level_transition: ReadStatus,
+    pub fetus: ReadStatus,
+    pub level_type: ReadStatus,
+}
+
+pub(crate) struct SmbProcess<M = GameMemory> {
+    memory: M,
+    pub read_status: ReadStatuses,
 
     pub playing: Pair<u8>,
     pub level_time: Pair<f32>,
@@ -11,36 +66,99 @@ pub(crate) struct SmbProcess {
     pub in_special_level: Pair<u8>,
     pub level_beaten: Pair<u8>,
     pub death_count: Pair<i32>,
+    /// The number of unlocked characters.
     pub characters: Pair<i32>,
     pub level: Pair<u8>,
     pub game_state: Pair<GameState>,
+    /// The last game state which is not [`GameState::Unknown`], so that transitions
+    /// between known states are noticed even if the game passes through unmapped
+    /// states in between.
+    pub known_game_state: Pair<GameState>,
     pub level_transition: Pair<u8>,
     pub fetus: Pair<u32>,
     pub level_type: Pair<i32>,
 }
 
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub(crate) enum GameVersion {
+    WindowsOriginal,
+    Windows1_2_5,
+    LinuxAmd64,
+}
+
+impl GameVersion {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Self::WindowsOriginal => "Windows (original)",
+            Self::Windows1_2_5 => "Windows (1.2.5)",
+            Self::LinuxAmd64 => "Linux (amd64)",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub(crate) enum AttachError {
+    ProcessNotFound,
+    MainModuleNotFound,
+    UnknownVersion { main_module_size: u64 },
+}
+
+impl fmt::Display for AttachError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ProcessNotFound => write!(f, "Game process not found"),
+            Self::MainModuleNotFound => write!(f, "Main module of the game not found"),
+            Self::UnknownVersion { main_module_size } => write!(
+                f,
+                "Unsupported game version (main module size {main_module_size:#x})"
+            ),
+        }
+    }
+}
+
 impl SmbProcess {
-    pub(crate) fn try_attach() -> Option<Self> {
-        let (process_name, process) =
-            ["SuperMeatBoy.exe", "SuperMeatBoy"]
-                .into_iter()
-                .find_map(|name| {
-                    let process = Process::attach(name)?;
-                    Some((name, process))
-                })?;
-
-        let main_module_size = process.get_module_size(process_name).ok()?;
-        let pointer_paths = match main_module_size {
-            0x34_2000 => PointerPaths::windows_og(&process),
-            0x33_c000 => PointerPaths::windows_1_2_5(&process),
-            0x21_9000 => PointerPaths::linux_amd64(&process),
-            _ => return None,
+    pub(crate) fn try_attach() -> Result<Self, AttachError> {
+        let (process_name, process) = ["SuperMeatBoy.exe", "SuperMeatBoy"]
+            .into_iter()
+            .find_map(|name| {
+                let process = Process::attach(name)?;
+                Some((name, process))
+            })
+            .ok_or(AttachError::ProcessNotFound)?;
+
+        let main_module_size = process
+            .get_module_size(process_name)
+            .map_err(|_| AttachError::MainModuleNotFound)?;
+        let version = match main_module_size {
+            0x34_2000 => GameVersion::WindowsOriginal,
+            0x33_c000 => GameVersion::Windows1_2_5,
+            0x21_9000 => GameVersion::LinuxAmd64,
+            _ => return Err(AttachError::UnknownVersion { main_module_size }),
+        };
+        let pointer_paths = match version {
+            GameVersion::WindowsOriginal => PointerPaths::windows_og(&process),
+            GameVersion::Windows1_2_5 => PointerPaths::windows_1_2_5(&process),
+            GameVersion::LinuxAmd64 => PointerPaths::linux_amd64(&process),
         }
-        .unwrap();
+        .map_err(|_| AttachError::MainModuleNotFound)?;
 
-        Some(Self {
+        Ok(Self::new(GameMemory {
             process,
+            version,
             pointer_paths,
+        }))
+    }
+
+    pub(crate) fn version(&self) -> GameVersion {
+        self.memory.version
+    }
+}
+
+impl<M: Memory> SmbProcess<M> {
+    pub(crate) fn new(memory: M) -> Self {
+        Self {
+            memory,
+            read_status: ReadStatuses::default(),
             playing: Pair::default(),
             level_time: Pair::default(),
             world: Pair::default(),
@@ -54,30 +172,57 @@ impl SmbProcess {
                 old: GameState::TitleScreen,
                 current: GameState::TitleScreen,
             },
+            known_game_state: Pair {
+                old: GameState::TitleScreen,
+                current: GameState::TitleScreen,
+            },
             level_transition: Pair::default(),
             fetus: Pair::default(),
             level_type: Pair::default(),
-        })
+        }
     }
 
     pub(crate) fn is_running(&self) -> bool {
-        self.process.is_open()
+        self.memory.is_open()
     }
 
     pub(crate) fn update_values(&mut self) {
-        fn update<T>(process: &Process, field: &mut Pair<T>, pointer_path: DeepPointer<8>)
-        where
-            T: bytemuck::CheckedBitPattern,
-        {
-            if let Ok(value) = pointer_path.deref(process) {
-                field.old = field.current;
-                field.current = value;
+        // Variables which were not freshly read on both this tick and the last one
+        // never appear to change, as such a change could be spurious.
+        fn update<T: Copy>(field: &mut Pair<T>, status: &mut ReadStatus, value: Option<T>) {
+            match (value, *status) {
+                (Some(value), ReadStatus::Failed { .. }) => {
+                    field.old = value;
+                    field.current = value;
+                    *status = ReadStatus::Recovered;
+                }
+                (Some(value), _) => {
+                    field.old = field.current;
+                    field.current = value;
+                    *status = ReadStatus::Ok;
+                }
+                (None, ReadStatus::Failed { ticks }) => {
+                    field.old = field.current;
+                    *status = ReadStatus::Failed {
+                        ticks: ticks.saturating_add(1),
+                    };
+                }
+                (None, _) => {
+                    field.old = field.current;
+                    *status = ReadStatus::Failed { ticks: 1 };
+                }
             }
         }
 
+        let values = self.memory.read_values();
+
         macro_rules! update {
             ($field:ident) => {
-                update(&self.process, &mut self.$field, self.pointer_paths.$field);
+                update(
+                    &mut self.$field,
+                    &mut self.read_status.$field,
+                    values.$field,
+                );
             };
         }
 
@@ -91,16 +236,157 @@ impl SmbProcess {
         update!(characters);
         update!(level);
         update!(game_state);
+        self.known_game_state.old = self.known_game_state.current;
+        if !matches!(self.game_state.current, GameState::Unknown(_)) {
+            self.known_game_state.current = self.game_state.current;
+            if self.read_status.game_state == ReadStatus::Recovered {
+                self.known_game_state.old = self.known_game_state.current;
+            }
+        }
         update!(level_transition);
         update!(fetus);
         update!(level_type);
     }
+
+    /// Writes the names of the variables which have failed to be read on exactly the
+    /// last `ticks` updates as ` <variable>`.
+    pub(crate) fn write_failures(&self, writer: &mut impl fmt::Write, ticks: u32) -> fmt::Result {
+        macro_rules! write_failure {
+            ($field:ident) => {
+                if self.read_status.$field == (ReadStatus::Failed { ticks }) {
+                    write!(writer, " {}", stringify!($field))?;
+                }
+            };
+        }
+
+        write_failure!(playing);
+        write_failure!(level_time);
+        write_failure!(world);
+        write_failure!(not_in_cutscene);
+        write_failure!(in_special_level);
+        write_failure!(level_beaten);
+        write_failure!(death_count);
+        write_failure!(characters);
+        write_failure!(level);
+        write_failure!(game_state);
+        write_failure!(level_transition);
+        write_failure!(fetus);
+        write_failure!(level_type);
+        Ok(())
+    }
+
+    /// Writes the variables which changed on the last update as ` <variable>=<value>`
+    /// pairs, in the format of the `traces` used by the tests. Variables which started
+    /// failing to be read are written as ` <variable>=-`. If `all` is set, all
+    /// variables are written instead.
+    pub(crate) fn write_changes(&self, writer: &mut impl fmt::Write, all: bool) -> fmt::Result {
+        macro_rules! write_change {
+            ($field:ident) => {
+                write_change!($field, self.$field.current);
+            };
+            ($field:ident, $value:expr) => {
+                let status = self.read_status.$field;
+                if let ReadStatus::Failed { ticks } = status {
+                    if all || ticks == 1 {
+                        write!(writer, " {}=-", stringify!($field))?;
+                    }
+                } else if all || status == ReadStatus::Recovered || self.$field.changed() {
+                    write!(writer, " {}={}", stringify!($field), $value)?;
+                }
+            };
+        }
+
+        write_change!(playing);
+        write_change!(level_time);
+        write_change!(world);
+        write_change!(not_in_cutscene);
+        write_change!(in_special_level);
+        write_change!(level_beaten);
+        write_change!(death_count);
+        write_change!(characters);
+        write_change!(level);
+        write_change!(game_state, u32::from(self.game_state.current));
+        write_change!(level_transition);
+        write_change!(fetus);
+        write_change!(level_type);
+        Ok(())
+    }
+}
+
+pub(crate) struct GameMemory {
+    process: Process,
+    version: GameVersion,
+    pointer_paths: PointerPaths,
+}
+
+impl Memory for GameMemory {
+    fn is_open(&self) -> bool {
+        self.process.is_open()
+    }
+
+    fn read_values(&mut self) -> Values {
+        macro_rules! read {
+            ($field:ident) => {
+                self.pointer_paths.$field.deref(&self.process).ok()
+            };
+        }
+
+        Values {
+            playing: read!(playing),
+            level_time: read!(level_time),
+            world: read!(world),
+            not_in_cutscene: read!(not_in_cutscene),
+            in_special_level: read!(in_special_level),
+            level_beaten: read!(level_beaten),
+            death_count: read!(death_count),
+            characters: read!(characters),
+            level: read!(level),
+            game_state: self
+                .pointer_paths
+                .game_state
+                .deref::<u32>(&self.process)
+                .ok()
+                .map(GameState::from),
+            level_transition: read!(level_transition),
+            fetus: read!(fetus),
+            level_type: read!(level_type),
+        }
+    }
+}
+
+macro_rules! game_states {
+    ($($state:ident = $value:literal,)*) => {
+        #[derive(Clone, Copy, Eq, PartialEq)]
+        pub(crate) enum GameState {
+            $($state,)*
+            /// A state whose meaning has not been determined yet. The values of the
+            /// credits, options, pause menu, cutscenes and warp zone entry have not
+            /// been identified, so these states are all unknown. Recorded traces
+            /// contain the raw value, which allows mapping them later.
+            Unknown(u32),
+        }
+
+        impl From<u32> for GameState {
+            fn from(value: u32) -> Self {
+                match value {
+                    $($value => Self::$state,)*
+                    _ => Self::Unknown(value),
+                }
+            }
+        }
+
+        impl From<GameState> for u32 {
+            fn from(state: GameState) -> Self {
+                match state {
+                    $(GameState::$state => $value,)*
+                    GameState::Unknown(value) => value,
+                }
+            }
+        }
+    };
 }
 
-#[non_exhaustive]
-#[repr(u32)]
-#[derive(bytemuck::CheckedBitPattern, Clone, Copy, Eq, PartialEq)]
-pub(crate) enum GameState {
+game_states! {
     Playing = 0,
     LevelSelection = 1,
     CharacterSelection = 4,
@@ -0,0 +1,1016 @@
+use crate::{
+    auto_splitter::AutoSplitter,
+    level::{Level, Side},
+    level_splits::LevelSplits,
+    runtime::Runtime,
+    smb_process::{GameState, Memory, SmbProcess, Values},
+    Category, Ending, GameTimeMode, Settings, StartSaveFile, TickRate,
+};
+use asr::{time::Duration, timer::TimerState};
+use std::{cell::RefCell, collections::HashMap, fmt::Debug, rc::Rc, str::FromStr};
+
+/// Replays a trace of game variables, one tick per [`Memory::read_values`] call.
+///
+/// Each `trace <tick> <variable>=<value> ...` line of a trace sets the listed
+/// variables on the given tick, with `-` making reads of the variable fail.
+/// Variables keep their values until they are set again, and all other lines are
+/// ignored.
+struct TraceMemory<'trace> {
+    changes: Vec<(u64, &'trace str)>,
+    values: Values,
+    next_tick: u64,
+    log: Rc<RefCell<Log>>,
+}
+
+impl<'trace> TraceMemory<'trace> {
+    fn new(trace: &'trace str, log: Rc<RefCell<Log>>) -> Self {
+        let changes = trace
+            .lines()
+            .filter_map(|line| line.strip_prefix("trace "))
+            .map(|line| {
+                let (tick, assignments) = line.split_once(' ').unwrap_or((line, ""));
+                (tick.parse().unwrap(), assignments)
+            })
+            .collect();
+
+        Self {
+            changes,
+            values: Values::default(),
+            next_tick: 0,
+            log,
+        }
+    }
+
+    fn apply(&mut self, assignments: &str) {
+        fn parse<T: FromStr<Err: Debug>>(value: &str) -> Option<T> {
+            (value != "-").then(|| value.parse().unwrap())
+        }
+
+        for assignment in assignments.split_whitespace() {
+            let (variable, value) = assignment.split_once('=').unwrap();
+            let values = &mut self.values;
+            match variable {
+                "playing" => values.playing = parse(value),
+                "level_time" => values.level_time = parse(value),
+                "world" => values.world = parse(value),
+                "not_in_cutscene" => values.not_in_cutscene = parse(value),
+                "in_special_level" => values.in_special_level = parse(value),
+                "level_beaten" => values.level_beaten = parse(value),
+                "death_count" => values.death_count = parse(value),
+                "characters" => values.characters = parse(value),
+                "level" => values.level = parse(value),
+                "game_state" => values.game_state = parse::<u32>(value).map(GameState::from),
+                "level_transition" => values.level_transition = parse(value),
+                "fetus" => values.fetus = parse(value),
+                "level_type" => values.level_type = parse(value),
+                "timer" => {}
+                _ => panic!("unknown variable in trace: {variable}"),
+            }
+        }
+    }
+}
+
+impl Memory for TraceMemory<'_> {
+    fn is_open(&self) -> bool {
+        self.changes
+            .last()
+            .is_some_and(|&(last_tick, _)| self.next_tick <= last_tick)
+    }
+
+    fn read_values(&mut self) -> Values {
+        let tick = self.next_tick;
+        let assignments = self
+            .changes
+            .iter()
+            .filter(|(change_tick, _)| *change_tick == tick)
+            .map(|&(_, assignments)| assignments)
+            .collect::<Vec<_>>();
+        for assignments in assignments {
+            self.apply(assignments);
+        }
+
+        self.log.borrow_mut().tick = tick;
+        self.next_tick += 1;
+        self.values
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Event {
+    Start,
+    Split,
+    Reset,
+}
+
+/// A change made by the user of the timer before a tick of a replay. Actions on the
+/// timer are not logged as events, which only record the actions of the auto
+/// splitter.
+#[derive(Clone, Copy)]
+enum UserAction {
+    ChangeSettings(fn(&mut Settings)),
+    Start,
+    Split,
+    SkipSplit,
+    UndoSplit,
+    Pause,
+    Resume,
+    Reset,
+}
+
+#[derive(Default)]
+struct Log {
+    tick: u64,
+    events: Vec<(u64, Event)>,
+    game_time: Option<Duration>,
+    game_time_pauses: Vec<(u64, bool)>,
+    variables: HashMap<String, String>,
+    int_variables: HashMap<String, i32>,
+    messages: Vec<String>,
+    stored_settings: HashMap<String, f64>,
+}
+
+/// A timer with a fixed number of segments, shared by the auto splitter and the
+/// user of a replay.
+struct TestTimer {
+    state: TimerState,
+    segment_count: u64,
+    split_index: u64,
+}
+
+impl TestTimer {
+    fn advance_split(&mut self) {
+        self.split_index += 1;
+        if self.split_index == self.segment_count {
+            self.state = TimerState::Ended;
+        }
+    }
+
+    fn apply_user_action(&mut self, action: UserAction) {
+        match action {
+            // Settings are changed when the runtime loads them
+            UserAction::ChangeSettings(_) => {}
+            UserAction::Start => {
+                if self.state == TimerState::NotRunning {
+                    self.state = TimerState::Running;
+                    self.split_index = 0;
+                }
+            }
+            UserAction::Split => {
+                if self.state == TimerState::Running {
+                    self.advance_split();
+                }
+            }
+            // The last split can't be skipped
+            UserAction::SkipSplit => {
+                if matches!(self.state, TimerState::Running | TimerState::Paused)
+                    && self.split_index + 1 < self.segment_count
+                {
+                    self.split_index += 1;
+                }
+            }
+            UserAction::UndoSplit => {
+                if self.state != TimerState::NotRunning && self.split_index > 0 {
+                    self.split_index -= 1;
+                    if self.state == TimerState::Ended {
+                        self.state = TimerState::Running;
+                    }
+                }
+            }
+            UserAction::Pause => {
+                if self.state == TimerState::Running {
+                    self.state = TimerState::Paused;
+                }
+            }
+            UserAction::Resume => {
+                if self.state == TimerState::Paused {
+                    self.state = TimerState::Running;
+                }
+            }
+            UserAction::Reset => self.state = TimerState::NotRunning,
+        }
+    }
+}
+
+/// The runtime of a replay, which records the actions taken by the auto splitter.
+struct TestRuntime {
+    timer: Rc<RefCell<TestTimer>>,
+    game_time_paused: bool,
+    settings_changes: Vec<(u64, UserAction)>,
+    next_tick: u64,
+    log: Rc<RefCell<Log>>,
+}
+
+impl TestRuntime {
+    fn push_event(&self, event: Event) {
+        let mut log = self.log.borrow_mut();
+        let tick = log.tick;
+        log.events.push((tick, event));
+    }
+
+    fn set_game_time_paused(&mut self, paused: bool) {
+        if self.game_time_paused != paused {
+            self.game_time_paused = paused;
+            let mut log = self.log.borrow_mut();
+            let tick = log.tick;
+            log.game_time_pauses.push((tick, paused));
+        }
+    }
+}
+
+impl Runtime for TestRuntime {
+    fn timer_state(&self) -> TimerState {
+        self.timer.borrow().state
+    }
+
+    fn current_split_index(&self) -> Option<u64> {
+        let timer = self.timer.borrow();
+        (timer.state != TimerState::NotRunning).then_some(timer.split_index)
+    }
+
+    fn start(&mut self) {
+        let mut timer = self.timer.borrow_mut();
+        if timer.state == TimerState::NotRunning {
+            timer.state = TimerState::Running;
+            timer.split_index = 0;
+            self.push_event(Event::Start);
+        }
+    }
+
+    fn split(&mut self) {
+        let mut timer = self.timer.borrow_mut();
+        if timer.state == TimerState::Running {
+            timer.advance_split();
+            self.push_event(Event::Split);
+        }
+    }
+
+    fn reset(&mut self) {
+        let mut timer = self.timer.borrow_mut();
+        if timer.state != TimerState::NotRunning {
+            timer.state = TimerState::NotRunning;
+            self.push_event(Event::Reset);
+        }
+    }
+
+    fn pause_game_time(&mut self) {
+        self.set_game_time_paused(true);
+    }
+
+    fn resume_game_time(&mut self) {
+        self.set_game_time_paused(false);
+    }
+
+    fn set_game_time(&mut self, time: Duration) {
+        self.log.borrow_mut().game_time = Some(time);
+    }
+
+    fn set_variable(&mut self, key: &str, value: &str) {
+        self.log
+            .borrow_mut()
+            .variables
+            .insert(key.to_owned(), value.to_owned());
+    }
+
+    fn set_variable_int(&mut self, key: &str, value: i32) {
+        self.log
+            .borrow_mut()
+            .int_variables
+            .insert(key.to_owned(), value);
+    }
+
+    fn set_variable_float(&mut self, _key: &str, _value: f32) {}
+
+    fn print_message(&mut self, message: &str) {
+        self.log.borrow_mut().messages.push(message.to_owned());
+    }
+
+    fn set_tick_rate(&mut self, _ticks_per_second: f64) {}
+
+    // The settings are loaded before every tick, after the user has acted on the timer
+    fn load_settings(&mut self, settings: &mut Settings, _level_splits: &mut LevelSplits) {
+        let tick = self.next_tick;
+        self.next_tick += 1;
+
+        for &(_, action) in self
+            .settings_changes
+            .iter()
+            .filter(|&&(action_tick, _)| action_tick == tick)
+        {
+            if let UserAction::ChangeSettings(change) = action {
+                change(settings);
+            }
+        }
+    }
+
+    fn load_setting_f64(&self, key: &str) -> Option<f64> {
+        self.log.borrow().stored_settings.get(key).copied()
+    }
+
+    fn store_setting_f64(&mut self, key: &str, value: f64) {
+        self.log
+            .borrow_mut()
+            .stored_settings
+            .insert(key.to_owned(), value);
+    }
+}
+
+fn default_settings() -> Settings {
+    Settings {
+        category: Category::Custom,
+        reset_on_main_menu: false,
+        start_save_file: StartSaveFile::Any,
+        split_after_level: false,
+        iw_mode: false,
+        iw_mode_split_on_first_level: true,
+        il_mode: false,
+        ending: Ending::Dark,
+        split_before_boss_1: false,
+        split_before_boss_2: false,
+        split_before_boss_3: false,
+        split_before_boss_4: false,
+        split_before_boss_5: false,
+        split_before_boss_6: false,
+        split_on_character_unlock: false,
+        allow_repeated_splits: false,
+        freeze_death_counter_on_finish: false,
+        game_time_mode: GameTimeMode::Disabled,
+        tick_rate: TickRate::Hz120,
+        record_trace: false,
+    }
+}
+
+fn replay(trace: &str, settings: &mut Settings, segment_count: u64) -> Log {
+    replay_with(
+        trace,
+        settings,
+        &mut LevelSplits::new(),
+        segment_count,
+        Vec::new(),
+    )
+}
+
+fn replay_with(
+    trace: &str,
+    settings: &mut Settings,
+    level_splits: &mut LevelSplits,
+    segment_count: u64,
+    user_actions: Vec<(u64, UserAction)>,
+) -> Log {
+    let log = Rc::new(RefCell::new(Log::default()));
+    let timer = Rc::new(RefCell::new(TestTimer {
+        state: TimerState::NotRunning,
+        segment_count,
+        split_index: 0,
+    }));
+    let (settings_changes, timer_actions): (Vec<_>, Vec<_>) = user_actions
+        .into_iter()
+        .partition(|(_, action)| matches!(action, UserAction::ChangeSettings(_)));
+    let runtime = TestRuntime {
+        timer: Rc::clone(&timer),
+        game_time_paused: false,
+        settings_changes,
+        next_tick: 0,
+        log: Rc::clone(&log),
+    };
+    let process = SmbProcess::new(TraceMemory::new(trace, Rc::clone(&log)));
+
+    {
+        let mut auto_splitter = AutoSplitter::new(process, runtime, settings, level_splits);
+        let mut tick = 0;
+        while auto_splitter.is_process_running() {
+            for &(_, action) in timer_actions
+                .iter()
+                .filter(|&&(action_tick, _)| action_tick == tick)
+            {
+                timer.borrow_mut().apply_user_action(action);
+            }
+            auto_splitter.update_settings();
+            auto_splitter.run_tick();
+            tick += 1;
+        }
+    }
+
+    Rc::into_inner(log).unwrap().into_inner()
+}
+
+const ANY_PERCENT: &str = include_str!("../traces/any_percent.trace");
+const DARK_ENDING: &str = include_str!("../traces/dark_ending.trace");
+const IW: &str = include_str!("../traces/iw.trace");
+const IL: &str = include_str!("../traces/il.trace");
+const REPEATED_BOSS: &str = include_str!("../traces/repeated_boss.trace");
+const FAILED_READ: &str = include_str!("../traces/failed_read.trace");
+const READ_OUTAGE: &str = include_str!("../traces/read_outage.trace");
+const MISSED_LEVEL_BEATEN: &str = include_str!("../traces/missed_level_beaten.trace");
+const EXIT_TO_MAP: &str = include_str!("../traces/exit_to_map.trace");
+const ONE_HUNDRED_SIX_PERCENT: &str = include_str!("../traces/one_hundred_six_percent.trace");
+const CHARACTER_UNLOCK: &str = include_str!("../traces/character_unlock.trace");
+const NAN_LEVEL_TIME: &str = include_str!("../traces/nan_level_time.trace");
+const UNKNOWN_GAME_STATE: &str = include_str!("../traces/unknown_game_state.trace");
+
+#[test]
+fn any_percent() {
+    let mut settings = Settings {
+        ending: Ending::Light,
+        ..default_settings()
+    };
+    let log = replay(ANY_PERCENT, &mut settings, 2);
+    assert_eq!(
+        log.events,
+        [(10, Event::Start), (60, Event::Split), (75, Event::Split)],
+    );
+    assert_eq!(log.int_variables["deaths"], 3);
+    assert_eq!(log.int_variables["deaths_split"], 0);
+    assert_eq!(log.int_variables["deaths_level"], 1);
+    assert_eq!(log.int_variables["deaths_previous_level"], 1);
+}
+
+#[test]
+fn level_variables() {
+    let mut settings = default_settings();
+    let log = replay(DARK_ENDING, &mut settings, 2);
+    assert_eq!(log.variables["level"], "6-Boss The End");
+    assert_eq!(log.variables["world"], "6 The End");
+
+    let log = replay(IL, &mut settings, 1);
+    assert_eq!(log.variables["level"], "1-4 The Forest (Light)");
+}
+
+#[test]
+fn level_names_with_garbage_index() {
+    let level = Level::new(1, u8::MAX, 0);
+    assert_eq!(level.to_string(), "1-256 The Forest (Light)");
+    assert_eq!(level.short_name().to_string(), "1-256");
+}
+
+#[test]
+fn category_defaults() {
+    // Any%, Any% Glitchless and All Bandages start on new save files and end with the
+    // light ending by default
+    let mut settings = Settings {
+        category: Category::LightEnding,
+        start_save_file: StartSaveFile::CategoryDefault,
+        ending: Ending::CategoryDefault,
+        ..default_settings()
+    };
+    let log = replay(ANY_PERCENT, &mut settings, 100);
+    assert!(log.events.is_empty());
+    let log = replay(DARK_ENDING, &mut settings, 100);
+    assert_eq!(
+        log.events,
+        [(5, Event::Start), (45, Event::Split), (50, Event::Reset)],
+    );
+
+    // 106% and 100% start on new save files and end with the dark ending by default
+    settings.category = Category::DarkEnding;
+    let log = replay(ANY_PERCENT, &mut settings, 100);
+    assert!(log.events.is_empty());
+    let log = replay(DARK_ENDING, &mut settings, 100);
+    assert_eq!(
+        log.events,
+        [
+            (5, Event::Start),
+            (30, Event::Split),
+            (45, Event::Split),
+            (50, Event::Reset)
+        ],
+    );
+
+    // All Dark World starts on any save file and ends with the dark ending by default
+    settings.category = Category::Custom;
+    let log = replay(ANY_PERCENT, &mut settings, 100);
+    assert_eq!(
+        log.events,
+        [(10, Event::Start), (60, Event::Split), (80, Event::Reset)],
+    );
+}
+
+#[test]
+fn category_defaults_can_be_overridden() {
+    let mut settings = Settings {
+        category: Category::LightEnding,
+        start_save_file: StartSaveFile::Any,
+        ending: Ending::CategoryDefault,
+        ..default_settings()
+    };
+    let log = replay(ANY_PERCENT, &mut settings, 2);
+    assert_eq!(
+        log.events,
+        [(10, Event::Start), (60, Event::Split), (75, Event::Split)],
+    );
+
+    settings.ending = Ending::Dark;
+    let log = replay(DARK_ENDING, &mut settings, 2);
+    assert_eq!(
+        log.events,
+        [(5, Event::Start), (30, Event::Split), (45, Event::Split)],
+    );
+
+    settings.iw_mode = true;
+    let log = replay(IW, &mut settings, 1);
+    assert_eq!(log.events[0].1, Event::Start);
+}
+
+#[test]
+fn used_save_file_does_not_start() {
+    let mut settings = Settings {
+        start_save_file: StartSaveFile::New,
+        ..default_settings()
+    };
+    let log = replay(ANY_PERCENT, &mut settings, 2);
+    assert!(log.events.is_empty());
+
+    let log = replay(DARK_ENDING, &mut settings, 2);
+    assert_eq!(log.events[0], (5, Event::Start));
+}
+
+#[test]
+fn any_percent_split_after_level() {
+    let mut settings = Settings {
+        ending: Ending::Light,
+        split_after_level: true,
+        ..default_settings()
+    };
+    let log = replay(ANY_PERCENT, &mut settings, 100);
+    assert_eq!(
+        log.events,
+        [
+            (10, Event::Start),
+            (40, Event::Split),
+            (60, Event::Split),
+            (75, Event::Split),
+            (80, Event::Reset),
+        ],
+    );
+}
+
+#[test]
+fn any_percent_level_split() {
+    let mut settings = Settings {
+        ending: Ending::Light,
+        ..default_settings()
+    };
+    let mut level_splits = LevelSplits::new();
+    level_splits.set_enabled(
+        Level::Normal {
+            world: 1,
+            index: 0,
+            side: Side::Light,
+        },
+        true,
+    );
+    let log = replay_with(ANY_PERCENT, &mut settings, &mut level_splits, 3, Vec::new());
+    assert_eq!(
+        log.events,
+        [
+            (10, Event::Start),
+            (40, Event::Split),
+            (60, Event::Split),
+            (75, Event::Split),
+        ],
+    );
+
+    // Indices out of range must not be mistaken for other levels, such as the boss
+    assert!(!level_splits.is_enabled(Level::Normal {
+        world: 1,
+        index: 40,
+        side: Side::Light,
+    }));
+}
+
+#[test]
+fn any_percent_level_time_sum() {
+    let mut settings = Settings {
+        ending: Ending::Light,
+        game_time_mode: GameTimeMode::LevelTimeSum,
+        ..default_settings()
+    };
+    let log = replay(ANY_PERCENT, &mut settings, 2);
+    assert_eq!(log.game_time, Some(Duration::seconds_f64(5.5)));
+    assert_eq!(log.variables["level_time_sum_delta"], "-");
+    assert_eq!(
+        log.stored_settings.get("best_level_time_sum_custom"),
+        Some(&5.5),
+    );
+}
+
+#[test]
+fn any_percent_loads_removed() {
+    let mut settings = Settings {
+        ending: Ending::Light,
+        game_time_mode: GameTimeMode::LoadsRemoved,
+        ..default_settings()
+    };
+    let log = replay(ANY_PERCENT, &mut settings, 2);
+    assert_eq!(log.game_time, Some(Duration::ZERO));
+    assert_eq!(
+        log.game_time_pauses,
+        [
+            (10, true),
+            (15, false),
+            (20, true),
+            (25, false),
+            (50, true),
+            (55, false),
+        ],
+    );
+}
+
+#[test]
+fn dark_ending() {
+    let mut settings = default_settings();
+    let log = replay(DARK_ENDING, &mut settings, 2);
+    assert_eq!(
+        log.events,
+        [(5, Event::Start), (30, Event::Split), (45, Event::Split)],
+    );
+}
+
+#[test]
+fn unknown_game_state_between_known_states() {
+    let mut settings = default_settings();
+    let log = replay(UNKNOWN_GAME_STATE, &mut settings, 100);
+    assert_eq!(log.events, [(5, Event::Start), (30, Event::Split)]);
+}
+
+#[test]
+fn one_hundred_six_percent() {
+    let mut settings = Settings {
+        category: Category::DarkEnding,
+        start_save_file: StartSaveFile::CategoryDefault,
+        ending: Ending::CategoryDefault,
+        ..default_settings()
+    };
+    let log = replay(ONE_HUNDRED_SIX_PERCENT, &mut settings, 2);
+    assert_eq!(
+        log.events,
+        [(5, Event::Start), (65, Event::Split), (80, Event::Split)],
+    );
+}
+
+#[test]
+fn light_ending_does_not_split_in_dark_ending_mode() {
+    let mut settings = default_settings();
+    let log = replay(ANY_PERCENT, &mut settings, 100);
+    assert_eq!(
+        log.events,
+        [(10, Event::Start), (60, Event::Split), (80, Event::Reset)],
+    );
+    // The levels of reset runs are logged too
+    assert_eq!(log.messages, ["level_history 1-1:5.500:2"]);
+}
+
+#[test]
+fn warp_zone_counters() {
+    let mut settings = Settings {
+        category: Category::DarkEnding,
+        ..default_settings()
+    };
+    let log = replay(ONE_HUNDRED_SIX_PERCENT, &mut settings, 2);
+    assert_eq!(log.int_variables["warp_zones_entered"], 2);
+    assert_eq!(log.int_variables["warp_zones_completed"], 1);
+
+    // The counters are cleared when the run is reset
+    let log = replay_with(
+        ONE_HUNDRED_SIX_PERCENT,
+        &mut settings,
+        &mut LevelSplits::new(),
+        2,
+        vec![(70, UserAction::Reset)],
+    );
+    assert_eq!(log.int_variables["warp_zones_entered"], 0);
+    assert_eq!(log.int_variables["warp_zones_completed"], 0);
+}
+
+#[test]
+fn character_unlock() {
+    let mut settings = Settings {
+        split_on_character_unlock: true,
+        ..default_settings()
+    };
+    let log = replay(CHARACTER_UNLOCK, &mut settings, 100);
+    assert_eq!(log.events, [(5, Event::Start), (25, Event::Split)]);
+    assert_eq!(log.int_variables["characters_unlocked"], 5);
+}
+
+#[test]
+fn iw() {
+    let mut settings = Settings {
+        iw_mode: true,
+        ..default_settings()
+    };
+    let log = replay(IW, &mut settings, 1);
+    assert_eq!(log.events, [(5, Event::Start), (25, Event::Split)]);
+}
+
+#[test]
+fn nan_level_time_sum_is_not_stored() {
+    let mut settings = Settings {
+        split_after_level: true,
+        game_time_mode: GameTimeMode::LevelTimeSum,
+        ..default_settings()
+    };
+    let log = replay(NAN_LEVEL_TIME, &mut settings, 1);
+    assert_eq!(log.events, [(0, Event::Start), (15, Event::Split)]);
+    assert_eq!(log.game_time, Some(Duration::ZERO));
+    assert_eq!(log.variables["level_time_sum_delta"], "-");
+    assert!(log.stored_settings.is_empty());
+}
+
+#[test]
+fn iw_level_time_sum_is_not_stored() {
+    let mut settings = Settings {
+        iw_mode: true,
+        game_time_mode: GameTimeMode::LevelTimeSum,
+        ..default_settings()
+    };
+    let log = replay(IW, &mut settings, 1);
+    assert_eq!(log.variables["level_time_sum_delta"], "-");
+    assert!(log.stored_settings.is_empty());
+}
+
+#[test]
+fn il() {
+    let mut settings = Settings {
+        il_mode: true,
+        game_time_mode: GameTimeMode::LevelTimeSum,
+        ..default_settings()
+    };
+    let log = replay(IL, &mut settings, 1);
+    assert_eq!(
+        log.events,
+        [
+            (10, Event::Start),
+            (15, Event::Reset),
+            (15, Event::Start),
+            (20, Event::Split),
+            (35, Event::Reset),
+            (35, Event::Start),
+            (40, Event::Reset),
+        ],
+    );
+    assert_eq!(
+        log.variables["last_level"],
+        "1-4 The Forest (Light): 7.250 (1 deaths)",
+    );
+    assert_eq!(log.messages, ["level_history 1-4:7.250:1"]);
+}
+
+#[test]
+fn repeated_boss() {
+    let mut settings = default_settings();
+    let log = replay(REPEATED_BOSS, &mut settings, 100);
+    assert_eq!(log.events, [(0, Event::Start), (15, Event::Split)]);
+
+    settings.allow_repeated_splits = true;
+    let log = replay(REPEATED_BOSS, &mut settings, 100);
+    assert_eq!(
+        log.events,
+        [(0, Event::Start), (15, Event::Split), (35, Event::Split)],
+    );
+}
+
+#[test]
+fn manual_start() {
+    let mut settings = Settings {
+        ending: Ending::Light,
+        ..default_settings()
+    };
+    let log = replay_with(
+        ANY_PERCENT,
+        &mut settings,
+        &mut LevelSplits::new(),
+        2,
+        vec![(6, UserAction::Start)],
+    );
+    assert_eq!(log.events, [(60, Event::Split), (75, Event::Split)]);
+    assert_eq!(log.int_variables["deaths"], 3);
+}
+
+#[test]
+fn manual_reset() {
+    let mut settings = Settings {
+        ending: Ending::Light,
+        ..default_settings()
+    };
+    let log = replay_with(
+        ANY_PERCENT,
+        &mut settings,
+        &mut LevelSplits::new(),
+        2,
+        vec![(32, UserAction::Reset)],
+    );
+    assert_eq!(log.events, [(10, Event::Start)]);
+    // Only the deaths after the reset are counted
+    assert_eq!(log.int_variables["deaths"], 2);
+}
+
+#[test]
+fn manual_split() {
+    let mut settings = Settings {
+        ending: Ending::Light,
+        ..default_settings()
+    };
+    let log = replay_with(
+        ANY_PERCENT,
+        &mut settings,
+        &mut LevelSplits::new(),
+        2,
+        vec![(62, UserAction::Split)],
+    );
+    assert_eq!(log.events, [(10, Event::Start), (60, Event::Split)]);
+    assert_eq!(
+        log.variables
+            .get("level_time_sum_delta")
+            .map(String::as_str),
+        Some("-")
+    );
+}
+
+#[test]
+fn manual_skip_and_undo() {
+    let mut settings = default_settings();
+    let log = replay_with(
+        REPEATED_BOSS,
+        &mut settings,
+        &mut LevelSplits::new(),
+        100,
+        vec![(5, UserAction::SkipSplit)],
+    );
+    assert_eq!(log.events, [(0, Event::Start), (15, Event::Split)]);
+
+    // Undoing the split of the boss allows splitting on it again
+    let log = replay_with(
+        REPEATED_BOSS,
+        &mut settings,
+        &mut LevelSplits::new(),
+        100,
+        vec![(5, UserAction::SkipSplit), (20, UserAction::UndoSplit)],
+    );
+    assert_eq!(
+        log.events,
+        [(0, Event::Start), (15, Event::Split), (35, Event::Split)],
+    );
+}
+
+#[test]
+fn deaths_split_after_undoing_several_splits() {
+    // Splitting after 0, 1 and 2 deaths, then going back to the split which started
+    // after 0 deaths. The trace stops before the boss is beaten.
+    let trace = format!(
+        "{}trace 59",
+        &ANY_PERCENT[..ANY_PERCENT.find("trace 60").unwrap()]
+    );
+    let log = replay_with(
+        &trace,
+        &mut default_settings(),
+        &mut LevelSplits::new(),
+        100,
+        vec![
+            (15, UserAction::Split),
+            (31, UserAction::Split),
+            (36, UserAction::Split),
+            (58, UserAction::UndoSplit),
+            (58, UserAction::UndoSplit),
+        ],
+    );
+    assert_eq!(log.int_variables["deaths"], 3);
+    assert_eq!(log.int_variables["deaths_split"], 3);
+}
+
+#[test]
+fn manual_pause_keeps_run_state() {
+    let mut settings = Settings {
+        ending: Ending::Light,
+        ..default_settings()
+    };
+    let log = replay_with(
+        ANY_PERCENT,
+        &mut settings,
+        &mut LevelSplits::new(),
+        2,
+        vec![(20, UserAction::Pause), (38, UserAction::Resume)],
+    );
+    assert_eq!(
+        log.events,
+        [(10, Event::Start), (60, Event::Split), (75, Event::Split)],
+    );
+    assert_eq!(log.int_variables["deaths"], 3);
+}
+
+#[test]
+fn split_while_paused_is_not_suppressed_later() {
+    let mut settings = default_settings();
+    let log = replay_with(
+        REPEATED_BOSS,
+        &mut settings,
+        &mut LevelSplits::new(),
+        100,
+        vec![(12, UserAction::Pause), (20, UserAction::Resume)],
+    );
+    assert_eq!(log.events, [(0, Event::Start), (35, Event::Split)]);
+}
+
+#[test]
+fn settings_changed_during_run_apply_after_reset() {
+    let mut settings = Settings {
+        ending: Ending::Light,
+        ..default_settings()
+    };
+    let log = replay_with(
+        ANY_PERCENT,
+        &mut settings,
+        &mut LevelSplits::new(),
+        100,
+        vec![(
+            30,
+            UserAction::ChangeSettings(|settings| {
+                settings.split_after_level = true;
+                settings.iw_mode = true;
+            }),
+        )],
+    );
+    assert_eq!(
+        log.events,
+        [
+            (10, Event::Start),
+            (60, Event::Split),
+            (75, Event::Split),
+            (80, Event::Reset),
+        ],
+    );
+    assert!(settings.split_after_level && settings.iw_mode);
+}
+
+#[test]
+fn failed_read() {
+    let mut settings = Settings {
+        split_after_level: true,
+        ..default_settings()
+    };
+    let log = replay(FAILED_READ, &mut settings, 100);
+    assert_eq!(log.events, [(0, Event::Start), (25, Event::Split)]);
+}
+
+#[test]
+fn read_outage() {
+    let mut settings = default_settings();
+    let log = replay(READ_OUTAGE, &mut settings, 1);
+    assert_eq!(
+        log.messages,
+        ["Failed to read for 120 ticks: death_count characters"],
+    );
+}
+
+#[test]
+fn missed_level_beaten() {
+    let mut settings = default_settings();
+    let log = replay(MISSED_LEVEL_BEATEN, &mut settings, 1);
+    assert_eq!(
+        log.messages,
+        ["Missed level_beaten changing to 1 for the level finished on tick 15"],
+    );
+}
+
+#[test]
+fn exit_to_map() {
+    let mut settings = Settings {
+        split_after_level: true,
+        ..default_settings()
+    };
+    let log = replay(EXIT_TO_MAP, &mut settings, 100);
+    assert_eq!(log.events, [(0, Event::Start), (20, Event::Split)]);
+    assert!(log.messages.is_empty());
+}
+
+#[test]
+fn recorded_trace_with_failed_reads_replays_identically() {
+    let mut settings = Settings {
+        split_after_level: true,
+        record_trace: true,
+        ..default_settings()
+    };
+    let log = replay(FAILED_READ, &mut settings, 100);
+    let recorded_trace = log.messages.join("\n");
+
+    settings.record_trace = false;
+    let replayed_log = replay(&recorded_trace, &mut settings, 100);
+    assert_eq!(replayed_log.events, log.events);
+}
+
+#[test]
+fn recorded_trace_replays_identically() {
+    let mut settings = Settings {
+        split_after_level: true,
+        record_trace: true,
+        ..default_settings()
+    };
+    let log = replay(ANY_PERCENT, &mut settings, 100);
+    let recorded_trace = log.messages.join("\n");
+
+    let mut settings = Settings {
+        split_after_level: true,
+        ..default_settings()
+    };
+    let replayed_log = replay(&recorded_trace, &mut settings, 100);
+    assert_eq!(replayed_log.events, log.events);
+}